@@ -0,0 +1,55 @@
+mod field;
+mod list;
+mod map;
+mod registry;
+mod utils;
+mod validate;
+mod validate_proto;
+
+pub use registry::{Args, CompiledValidator, Violation, Violations};
+
+use anyhow::Result;
+use prost_reflect::{DynamicMessage, MessageDescriptor};
+use registry::REGISTRY;
+use std::any::Any;
+use std::sync::Arc;
+
+/// Validates `msg` against its generated rules, failing fast on the first broken field.
+pub fn validate(msg: &DynamicMessage) -> Result<()> {
+    REGISTRY.validate(msg)
+}
+
+/// Like [`validate`], but instead of stopping at the first broken field runs the whole validation
+/// tree and collects every [`Violation`] encountered along the way.
+pub fn validate_collecting(msg: &DynamicMessage) -> std::result::Result<(), Violations> {
+    REGISTRY.validate_collecting(msg)
+}
+
+/// Like [`validate`], but makes `ctx` available to custom validators registered via
+/// [`register_custom`] (through [`Args::ctx`]).
+pub fn validate_with<C: Any + Send + Sync>(msg: &DynamicMessage, ctx: &C) -> Result<()> {
+    REGISTRY.validate_with(msg, ctx)
+}
+
+/// Eagerly resolves `desc` and every message type reachable through its fields into a
+/// self-contained [`CompiledValidator`] that never locks once built. Prefer this over repeated
+/// [`validate`] calls for callers that validate the same message type in a loop.
+pub fn compile(desc: &MessageDescriptor) -> Result<CompiledValidator> {
+    REGISTRY.compile(desc)
+}
+
+/// Registers a user-defined validator that runs after the generated rules for the message (or
+/// field) identified by `full_name`, e.g. `"mypackage.MyMessage"` or
+/// `"mypackage.MyMessage.my_field"`. Drops the target's cached validator so the new validator takes
+/// effect on the next [`validate`] call, even if the target was validated before. A
+/// [`CompiledValidator`] built earlier by [`compile`] keeps the validators it was built with.
+pub fn register_custom(full_name: &str, f: impl Fn(&Args) -> Result<()> + Send + Sync + 'static) {
+    REGISTRY.register_custom(full_name, Arc::new(f));
+}
+
+/// Registers a cross-field constraint expression (e.g. `"end_time > start_time"` or
+/// `"required_together(client_id, client_secret)"`) that runs against the whole message
+/// identified by `full_name`, after the generated field rules and before any custom validators.
+pub fn register_message_constraint(full_name: &str, expr: &str) -> Result<()> {
+    REGISTRY.register_message_constraint(full_name, expr)
+}
@@ -5,30 +5,184 @@ use crate::utils::{get_field_rules, is_set};
 use crate::validate::{IsTrue, VALIDATION_DISABLED, VALIDATION_IGNORED, VALIDATION_ONE_OF_RULES};
 use crate::validate_proto::FieldRules;
 use anyhow::{format_err, Result};
-use no_deadlocks::RwLock;
 use once_cell::sync::Lazy;
-use prost_reflect::{DynamicMessage, MessageDescriptor, OneofDescriptor, ReflectMessage};
+use parking_lot::RwLock;
+use prost_reflect::{DynamicMessage, FieldDescriptor, MessageDescriptor, OneofDescriptor, ReflectMessage};
+use std::any::Any;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 use std::sync::Arc;
 
-pub(crate) struct Args<'a> {
+/// A single failed constraint, reported with the fully-qualified path of the field it applies to.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub field_path: String,
+    pub constraint: &'static str,
+    pub message: String,
+}
+
+/// The full set of violations collected by [`Registry::validate_collecting`].
+#[derive(Debug, Default, Clone)]
+pub struct Violations(pub Vec<Violation>);
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", v.field_path, v.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Violations {}
+
+#[derive(Clone, Copy)]
+pub struct Args<'a> {
     pub(crate) m: &'a HashMap<String, ValidationFn>,
     pub(crate) msg: &'a DynamicMessage,
+    pub(crate) path: &'a str,
+    pub(crate) violations: Option<&'a RefCell<Vec<Violation>>>,
+    /// Caller-supplied context (see [`Registry::validate_with`]). Only custom validators registered
+    /// via [`Registry::register_custom`] are expected to downcast and read this; generated rules
+    /// ignore it.
+    pub(crate) ctx: Option<&'a dyn Any>,
+    /// Set when a custom validator was registered against a specific field (rather than the whole
+    /// message) via [`Registry::register_custom`], so the closure can resolve its own field value
+    /// off `msg` without needing to know its own name.
+    pub(crate) field: Option<&'a FieldDescriptor>,
+}
+
+impl<'a> Args<'a> {
+    /// The message currently being validated.
+    pub fn msg(&self) -> &'a DynamicMessage {
+        self.msg
+    }
+
+    /// The dotted path of the message currently being validated, relative to the message passed
+    /// to [`Registry::validate`] (empty at the top level). It does not include the name of the
+    /// field a field-scoped custom validator was registered against (see [`Args::field`]).
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+
+    /// The context passed to [`Registry::validate_with`], downcast to `C`. `None` if validation
+    /// was started without a context, or if `C` doesn't match the type that was passed in.
+    pub fn ctx<C: Any>(&self) -> Option<&'a C> {
+        self.ctx.and_then(<dyn Any>::downcast_ref::<C>)
+    }
+
+    /// The field a field-scoped custom validator was registered against, if any (see
+    /// [`Registry::register_custom`]).
+    pub fn field(&self) -> Option<&'a FieldDescriptor> {
+        self.field
+    }
 }
 
 pub(crate) type ValidationFn = Arc<dyn Fn(&Args) -> Result<()> + Send + Sync>;
 pub(crate) type FieldValidationFn<T> = Arc<dyn Fn(Option<T>, &FieldRules) -> Result<bool> + Send + Sync>;
-pub(crate) type NestedValidationFn<T> = Arc<dyn Fn(Option<T>, &FieldRules, &HashMap<String, ValidationFn>) -> Result<bool> + Send + Sync>;
+/// Validates a field that may itself embed a message. Takes the whole caller `&Args` (rather than
+/// just `m`) so that, when it recurses into [`Registry::do_validate_with`] for an embedded message,
+/// it can forward `path` (prefixed with this field's name via [`field_path`]), `violations` and
+/// `ctx` instead of re-starting a fresh, disconnected validation run.
+pub(crate) type NestedValidationFn<T> = Arc<dyn Fn(Option<T>, &FieldRules, &Args) -> Result<bool> + Send + Sync>;
 
 pub(crate) static REGISTRY: Lazy<Registry> = Lazy::new(|| Registry::default());
 
+/// Builds the field path for a violation, prefixing with the parent path when nested. Exposed so
+/// that [`NestedValidationFn`] implementations can prefix the path they forward to a nested
+/// message's [`Registry::do_validate_with`] call.
+pub(crate) fn field_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}.{name}")
+    }
+}
+
+/// Returns the full name of the message that owns the field identified by `full_name`, e.g.
+/// `"pkg.Msg.some_field"` -> `Some("pkg.Msg")`. `None` if `full_name` contains no dot; for a
+/// message full name such as `"pkg.Msg"` this returns its package (`Some("pkg")`).
+fn owning_message_full_name(full_name: &str) -> Option<&str> {
+    full_name.rsplit_once('.').map(|(owner, _)| owner)
+}
+
+/// Runs `f`; in fail-fast mode propagates its error, in collecting mode records it as a
+/// [`Violation`] and lets validation continue with the remaining fields.
+fn record(args: &Args, name: &str, constraint: &'static str, f: impl FnOnce() -> Result<()>) -> Result<()> {
+    match f() {
+        Ok(()) => Ok(()),
+        Err(e) => match args.violations {
+            Some(violations) => {
+                violations.borrow_mut().push(Violation { field_path: field_path(args.path, name), constraint, message: e.to_string() });
+                Ok(())
+            }
+            None => Err(e),
+        },
+    }
+}
+
 #[derive(Default, Clone)]
 pub(crate) struct Registry {
     m: Arc<RwLock<HashMap<String, ValidationFn>>>,
+    custom: Arc<RwLock<HashMap<String, Vec<ValidationFn>>>>,
+    message_constraints: Arc<RwLock<HashMap<String, Vec<MessageConstraint>>>>,
 }
 
 impl Registry {
+    /// Registers a user-defined validator that runs after the generated rules for the message (or
+    /// field) identified by `full_name`. Drops the target's cached validator so the new validator
+    /// takes effect on the next [`Registry::validate`] call, even if the target was validated
+    /// before. A [`CompiledValidator`] built earlier keeps the validators it was built with.
+    pub(crate) fn register_custom(&self, full_name: &str, f: Arc<dyn Fn(&Args) -> Result<()> + Send + Sync>) {
+        self.custom.write().entry(full_name.to_string()).or_default().push(f);
+        let _ = self.m.write().remove(full_name);
+        // `self.m` is only ever keyed by message full names, so when `full_name` identifies a
+        // field rather than a message, removing it above is a no-op. Also invalidate the owning
+        // message's entry so a field-scoped custom validator registered after that message was
+        // already compiled still takes effect on the next validate call.
+        if let Some(owner) = owning_message_full_name(full_name) {
+            let _ = self.m.write().remove(owner);
+        }
+    }
+
+    /// Registers a cross-field constraint expression (see [`parse_message_constraint`] for the
+    /// supported syntax) that runs against the whole message identified by `full_name`, after the
+    /// generated field rules. There is no `validate.proto` extension for this yet, so constraints
+    /// are registered programmatically rather than read off `MessageOptions`.
+    pub(crate) fn register_message_constraint(&self, full_name: &str, expr: &str) -> Result<()> {
+        let constraint = parse_message_constraint(expr)?;
+        self.message_constraints.write().entry(full_name.to_string()).or_default().push(constraint);
+        let _ = self.m.write().remove(full_name);
+        Ok(())
+    }
+
+    /// Eagerly resolves `desc` and every message type reachable through its fields into a
+    /// self-contained [`CompiledValidator`] that never locks once built. Prefer this over
+    /// [`Registry::validate`] for callers that validate the same message type in a loop.
+    pub(crate) fn compile(&self, desc: &MessageDescriptor) -> Result<CompiledValidator> {
+        let mut m = HashMap::new();
+        self.register_transitive(&mut m, desc)?;
+        Ok(CompiledValidator { target: desc.full_name().to_string(), m: Arc::new(m) })
+    }
+
+    fn register_transitive(&self, m: &mut HashMap<String, ValidationFn>, desc: &MessageDescriptor) -> Result<()> {
+        if m.contains_key(desc.full_name()) {
+            return Ok(());
+        }
+        self.register(m, desc)?;
+        for field in desc.fields() {
+            if let Some(nested) = field.kind().as_message() {
+                self.register_transitive(m, &nested)?;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn register(&self, m: &mut HashMap<String, ValidationFn>, desc: &MessageDescriptor) -> Result<()> {
         if m.get(desc.full_name()).is_some() {
             return Ok(());
@@ -62,43 +216,57 @@ impl Registry {
                         None => continue,
                     };
                     let validate_field = make_validate_field(m, &field, &rules);
-                    fns.push(Arc::new(move |Args { msg, m }| {
+                    let field_name = field.name().to_string();
+                    fns.push(Arc::new(move |args @ Args { msg, .. }| {
                         let val = msg.get_field(&field);
                         if !is_set(&val) {
                             return Ok(());
                         }
-                        validate_field(val, &rules, m)?;
-                        Ok(())
+                        record(args, &field_name, "field", || validate_field(val, &rules, args).map(|_| ()))
                     }));
                 }
                 let field = field.clone();
                 if desc.options().get_extension(&VALIDATION_ONE_OF_RULES).is_true() {
-                    fns.push(Arc::new(move |Args { msg, .. }| {
-                        let mut has = false;
-                        for field in field.containing_oneof().unwrap().fields() {
-                            let ok = is_set(&msg.get_field(&field));
-                            if ok {
-                                if has {
-                                    return Err(format_err!("oneof {} contains multiple values", field.containing_oneof().unwrap().name()));
+                    let oneof_name = desc.name().to_string();
+                    fns.push(Arc::new(move |args @ Args { msg, .. }| {
+                        record(args, &oneof_name, "oneof", || {
+                            let mut has = false;
+                            for field in field.containing_oneof().unwrap().fields() {
+                                let ok = is_set(&msg.get_field(&field));
+                                if ok {
+                                    if has {
+                                        return Err(format_err!("oneof {} contains multiple values", field.containing_oneof().unwrap().name()));
+                                    }
+                                    has = true;
                                 }
-                                has = true;
                             }
-                        }
-                        if !has {
-                            return Err(format_err!("oneof {} does not contains any value", field.containing_oneof().unwrap().name()));
-                        }
-                        Ok(())
+                            if !has {
+                                return Err(format_err!("oneof {} does not contains any value", field.containing_oneof().unwrap().name()));
+                            }
+                            Ok(())
+                        })
                     }))
                 }
                 continue;
             }
             if field.is_list() {
                 let validate_list = make_validate_list(m, field.clone(), &rules);
-                fns.push(Arc::new(move |Args { msg, m }| {
+                let field_name = field.name().to_string();
+                fns.push(Arc::new(move |args @ Args { msg, .. }| {
                     let v = msg.get_field(&field).as_list().map(|v| Box::new(v.to_owned()));
-                    for f in &validate_list {
+                    // Each entry in `validate_list` is one *rule* (min_items, pattern, ...) and
+                    // receives the whole list value, not a single element — there is no per-item
+                    // entry point here to recover which list entry actually failed, so every rule
+                    // is reported under the bare field name rather than a fabricated `[idx]` that
+                    // would really mean "rule position", not "list entry".
+                    for f in validate_list.iter() {
                         let v = v.clone();
-                        if !f(v, &rules, m)? {
+                        let mut keep_going = true;
+                        record(args, &field_name, "list", || {
+                            keep_going = f(v, &rules, args)?;
+                            Ok(())
+                        })?;
+                        if !keep_going {
                             break;
                         }
                     }
@@ -108,11 +276,19 @@ impl Registry {
             }
             if field.is_map() {
                 let validate_map = make_validate_map(m, field.clone(), &rules);
-                fns.push(Arc::new(move |Args { msg, m }| {
+                let field_name = field.name().to_string();
+                fns.push(Arc::new(move |args @ Args { msg, .. }| {
                     let v = msg.get_field(&field).as_map().map(|v| Box::new(v.to_owned()));
-                    for f in &validate_map {
+                    // Same caveat as the list case above: `validate_map` holds one closure per
+                    // rule, each given the whole map, so there is no real key to report here.
+                    for f in validate_map.iter() {
                         let v = v.clone();
-                        if !f(v, &rules, m)? {
+                        let mut keep_going = true;
+                        record(args, &field_name, "map", || {
+                            keep_going = f(v, &rules, args)?;
+                            Ok(())
+                        })?;
+                        if !keep_going {
                             break;
                         }
                     }
@@ -122,15 +298,37 @@ impl Registry {
             }
             let validate_field = make_validate_field(m, &field, &rules);
             let field = field.clone();
-            fns.push(Arc::new(move |Args { msg, m }| {
+            let field_name = field.name().to_string();
+            fns.push(Arc::new(move |args @ Args { msg, .. }| {
                 let v = msg.get_field(&field);
-                validate_field(v, &rules, m)?;
-                Ok(())
+                record(args, &field_name, "field", || validate_field(v, &rules, args).map(|_| ()))
             }));
         }
-        let _ = m.insert(desc.full_name().to_string(), Arc::new(move |v| {
+        if let Some(constraints) = self.message_constraints.read().get(desc.full_name()) {
+            for constraint in constraints.iter().cloned() {
+                fns.push(compile_message_constraint(constraint));
+            }
+        }
+        if let Some(custom_fns) = self.custom.read().get(desc.full_name()) {
+            for f in custom_fns.iter().cloned() {
+                fns.push(Arc::new(move |args: &Args| record(args, "custom", "custom", || f(args))));
+            }
+        }
+        for field in desc.fields() {
+            if let Some(custom_fns) = self.custom.read().get(field.full_name()) {
+                let field_name = field.name().to_string();
+                let field = field.clone();
+                for f in custom_fns.iter().cloned() {
+                    fns.push(Arc::new(move |args: &Args| {
+                        let field_args = Args { field: Some(&field), ..*args };
+                        record(&field_args, &field_name, "custom", || f(&field_args))
+                    }));
+                }
+            }
+        }
+        let _ = m.insert(desc.full_name().to_string(), Arc::new(move |args: &Args| {
             for f in &fns {
-                f(v)?;
+                f(args)?;
             }
             Ok(())
         }));
@@ -138,27 +336,391 @@ impl Registry {
     }
 
     pub(crate) fn validate(&self, msg: &DynamicMessage) -> Result<()> {
+        self.validate_inner(msg, "", None, None)
+    }
+
+    /// Like [`Registry::validate`], but instead of stopping at the first broken field it runs the
+    /// whole validation tree and collects every [`Violation`] encountered along the way.
+    pub(crate) fn validate_collecting(&self, msg: &DynamicMessage) -> std::result::Result<(), Violations> {
+        let violations = RefCell::new(Vec::new());
+        if let Err(e) = self.validate_inner(msg, "", Some(&violations), None) {
+            violations.borrow_mut().push(Violation { field_path: String::new(), constraint: "registry", message: e.to_string() });
+        }
+        let collected = violations.into_inner();
+        if collected.is_empty() {
+            Ok(())
+        } else {
+            Err(Violations(collected))
+        }
+    }
+
+    /// Like [`Registry::validate`], but makes `ctx` available to custom validators registered via
+    /// [`Registry::register_custom`] (through [`Args::ctx`]). Generated rules never look at `ctx`,
+    /// so the stateless fast path of [`Registry::validate`] is unaffected.
+    pub(crate) fn validate_with<C: Any + Send + Sync>(&self, msg: &DynamicMessage, ctx: &C) -> Result<()> {
+        self.validate_inner(msg, "", None, Some(ctx as &dyn Any))
+    }
+
+    fn validate_inner(&self, msg: &DynamicMessage, path: &str, violations: Option<&RefCell<Vec<Violation>>>, ctx: Option<&dyn Any>) -> Result<()> {
         {
-            let m = self.m.read().unwrap();
+            let m = self.m.read();
             if let Some(f) = m.get(msg.descriptor().full_name()) {
-                let _ = f(&Args { msg, m: &m })?;
-                return Ok(());
+                return f(&Args { msg, m: &m, path, violations, ctx, field: None });
             }
         }
         {
-            let mut m = self.m.write().unwrap();
+            let mut m = self.m.write();
             let desc = msg.descriptor();
             self.register(&mut m, &desc)?;
         }
-        self.validate(msg)
+        self.validate_inner(msg, path, violations, ctx)
     }
 
     pub(crate) fn do_validate(&self, msg: &DynamicMessage, m: &HashMap<String, ValidationFn>) -> Result<()> {
+        self.do_validate_with(msg, m, "", None, None)
+    }
+
+    /// Validates an embedded message. A [`NestedValidationFn`] recursing into a nested message
+    /// should call this with `args.m`, `field_path(args.path, field_name)`, `args.violations` and
+    /// `args.ctx` so the nested validation stays part of the same accumulating, context-aware run
+    /// instead of starting a fresh fail-fast one.
+    pub(crate) fn do_validate_with(
+        &self,
+        msg: &DynamicMessage,
+        m: &HashMap<String, ValidationFn>,
+        path: &str,
+        violations: Option<&RefCell<Vec<Violation>>>,
+        ctx: Option<&dyn Any>,
+    ) -> Result<()> {
         if let Some(f) = m.get(msg.descriptor().full_name()) {
-            let _ = f(&Args { msg, m })?;
-            Ok(())
+            f(&Args { msg, m, path, violations, ctx, field: None })
         } else {
             Err(format_err!("no validator for {}", msg.descriptor().full_name()))
         }
     }
 }
+
+/// A validator for one message type, precompiled by [`Registry::compile`] with its whole
+/// transitive message graph resolved ahead of time. Validating never locks.
+#[derive(Clone)]
+pub struct CompiledValidator {
+    target: String,
+    m: Arc<HashMap<String, ValidationFn>>,
+}
+
+impl CompiledValidator {
+    pub fn validate(&self, msg: &DynamicMessage) -> Result<()> {
+        self.run(msg, "", None, None)
+    }
+
+    pub fn validate_collecting(&self, msg: &DynamicMessage) -> std::result::Result<(), Violations> {
+        let violations = RefCell::new(Vec::new());
+        if let Err(e) = self.run(msg, "", Some(&violations), None) {
+            violations.borrow_mut().push(Violation { field_path: String::new(), constraint: "registry", message: e.to_string() });
+        }
+        let collected = violations.into_inner();
+        if collected.is_empty() {
+            Ok(())
+        } else {
+            Err(Violations(collected))
+        }
+    }
+
+    pub fn validate_with<C: Any + Send + Sync>(&self, msg: &DynamicMessage, ctx: &C) -> Result<()> {
+        self.run(msg, "", None, Some(ctx as &dyn Any))
+    }
+
+    fn run(&self, msg: &DynamicMessage, path: &str, violations: Option<&RefCell<Vec<Violation>>>, ctx: Option<&dyn Any>) -> Result<()> {
+        match self.m.get(&self.target) {
+            Some(f) => f(&Args { msg, m: &self.m, path, violations, ctx, field: None }),
+            None => Err(format_err!("no validator for {}", self.target)),
+        }
+    }
+}
+
+/// A message-level cross-field constraint, generalizing the `VALIDATION_ONE_OF_RULES` special
+/// case to arbitrary field comparisons and presence rules.
+#[derive(Debug, Clone, PartialEq)]
+enum MessageConstraint {
+    Compare { field_a: String, op: CompareOp, rhs: CompareRhs },
+    RequiredTogether(Vec<String>),
+    MutuallyExclusive(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CompareRhs {
+    Field(String),
+    /// The literal's type is decided once, here at parse time, based on whether it was quoted —
+    /// never re-derived later by guessing from its contents (a quoted `"00501"` must stay text,
+    /// not become the number 501).
+    Literal(CompareValue),
+}
+
+/// Parses one cross-field constraint expression, as passed to
+/// [`Registry::register_message_constraint`], e.g.
+/// `"end_time > start_time"`, `"required_together(client_id, client_secret)"` or
+/// `"mutually_exclusive(a, b)"`.
+fn parse_message_constraint(expr: &str) -> Result<MessageConstraint> {
+    let expr = expr.trim();
+    if let Some(fields) = expr.strip_prefix("required_together(").and_then(|s| s.strip_suffix(')')) {
+        return Ok(MessageConstraint::RequiredTogether(split_fields(fields)));
+    }
+    if let Some(fields) = expr.strip_prefix("mutually_exclusive(").and_then(|s| s.strip_suffix(')')) {
+        return Ok(MessageConstraint::MutuallyExclusive(split_fields(fields)));
+    }
+    for (token, op) in [
+        (">=", CompareOp::Gte),
+        ("<=", CompareOp::Lte),
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        (">", CompareOp::Gt),
+        ("<", CompareOp::Lt),
+    ] {
+        if let Some((lhs, rhs)) = expr.split_once(token) {
+            let field_a = lhs.trim().to_string();
+            let rhs = rhs.trim();
+            let rhs = if let Some(quoted) = strip_quotes(rhs) {
+                CompareRhs::Literal(CompareValue::Text(quoted.to_string()))
+            } else if let Ok(n) = rhs.parse::<f64>() {
+                CompareRhs::Literal(CompareValue::Number(n))
+            } else if rhs == "true" || rhs == "false" {
+                CompareRhs::Literal(CompareValue::Bool(rhs == "true"))
+            } else {
+                CompareRhs::Field(rhs.to_string())
+            };
+            return Ok(MessageConstraint::Compare { field_a, op, rhs });
+        }
+    }
+    Err(format_err!("unrecognized message constraint expression: {expr}"))
+}
+
+/// Strips a matching pair of single or double quotes, if present.
+fn strip_quotes(s: &str) -> Option<&str> {
+    s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')).or_else(|| s.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+}
+
+fn split_fields(s: &str) -> Vec<String> {
+    s.split(',').map(|p| p.trim().to_string()).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CompareValue {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+fn field_is_set(msg: &DynamicMessage, name: &str) -> Result<bool> {
+    let field = msg.descriptor().get_field_by_name(name).ok_or_else(|| format_err!("unknown field {name}"))?;
+    Ok(is_set(&msg.get_field(&field)))
+}
+
+fn field_value(msg: &DynamicMessage, name: &str) -> Result<CompareValue> {
+    let field = msg.descriptor().get_field_by_name(name).ok_or_else(|| format_err!("unknown field {name}"))?;
+    value_to_compare(&msg.get_field(&field))
+}
+
+fn value_to_compare(v: &prost_reflect::Value) -> Result<CompareValue> {
+    use prost_reflect::Value;
+    Ok(match v {
+        Value::I32(n) => CompareValue::Number(*n as f64),
+        Value::I64(n) => CompareValue::Number(*n as f64),
+        Value::U32(n) => CompareValue::Number(*n as f64),
+        Value::U64(n) => CompareValue::Number(*n as f64),
+        Value::F32(n) => CompareValue::Number(*n as f64),
+        Value::F64(n) => CompareValue::Number(*n),
+        Value::Bool(b) => CompareValue::Bool(*b),
+        Value::String(s) => CompareValue::Text(s.clone()),
+        other => return Err(format_err!("unsupported value for cross-field comparison: {other:?}")),
+    })
+}
+
+fn compare(a: CompareValue, op: CompareOp, b: CompareValue) -> Result<()> {
+    let ok = match (a, b) {
+        (CompareValue::Number(a), CompareValue::Number(b)) => match op {
+            CompareOp::Lt => a < b,
+            CompareOp::Lte => a <= b,
+            CompareOp::Gt => a > b,
+            CompareOp::Gte => a >= b,
+            CompareOp::Eq => a == b,
+            CompareOp::Ne => a != b,
+        },
+        (CompareValue::Text(a), CompareValue::Text(b)) => match op {
+            CompareOp::Lt => a < b,
+            CompareOp::Lte => a <= b,
+            CompareOp::Gt => a > b,
+            CompareOp::Gte => a >= b,
+            CompareOp::Eq => a == b,
+            CompareOp::Ne => a != b,
+        },
+        (CompareValue::Bool(a), CompareValue::Bool(b)) => match op {
+            CompareOp::Eq => a == b,
+            CompareOp::Ne => a != b,
+            _ => return Err(format_err!("ordering operators do not apply to bool fields")),
+        },
+        _ => return Err(format_err!("cannot compare fields of different types")),
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format_err!("constraint not satisfied"))
+    }
+}
+
+fn compile_message_constraint(constraint: MessageConstraint) -> ValidationFn {
+    match constraint {
+        MessageConstraint::RequiredTogether(fields) => {
+            let name = format!("required_together({})", fields.join(", "));
+            Arc::new(move |args: &Args| {
+                record(args, &name, "required_together", || {
+                    let present = fields.iter().map(|f| field_is_set(args.msg, f)).collect::<Result<Vec<bool>>>()?;
+                    if present.iter().any(|&b| b) && !present.iter().all(|&b| b) {
+                        return Err(format_err!("fields {} must be set together", fields.join(", ")));
+                    }
+                    Ok(())
+                })
+            })
+        }
+        MessageConstraint::MutuallyExclusive(fields) => {
+            let name = format!("mutually_exclusive({})", fields.join(", "));
+            Arc::new(move |args: &Args| {
+                record(args, &name, "mutually_exclusive", || {
+                    let set_count = fields.iter().map(|f| field_is_set(args.msg, f)).collect::<Result<Vec<bool>>>()?.into_iter().filter(|&b| b).count();
+                    if set_count > 1 {
+                        return Err(format_err!("only one of {} may be set", fields.join(", ")));
+                    }
+                    Ok(())
+                })
+            })
+        }
+        MessageConstraint::Compare { field_a, op, rhs } => {
+            let name = field_a.clone();
+            Arc::new(move |args: &Args| {
+                record(args, &name, "compare", || {
+                    let a = field_value(args.msg, &field_a)?;
+                    let b = match &rhs {
+                        CompareRhs::Field(f) => field_value(args.msg, f)?,
+                        CompareRhs::Literal(v) => v.clone(),
+                    };
+                    compare(a, op, b)
+                })
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_path_without_prefix_is_just_the_name() {
+        assert_eq!(field_path("", "amount"), "amount");
+    }
+
+    #[test]
+    fn field_path_with_prefix_is_dot_joined() {
+        assert_eq!(field_path("order", "amount"), "order.amount");
+        assert_eq!(field_path("order.item", "sku"), "order.item.sku");
+    }
+
+    #[test]
+    fn owning_message_full_name_for_a_field_is_its_message() {
+        assert_eq!(owning_message_full_name("pkg.Msg.some_field"), Some("pkg.Msg"));
+        assert_eq!(owning_message_full_name("pkg.Outer.Inner.some_field"), Some("pkg.Outer.Inner"));
+    }
+
+    #[test]
+    fn owning_message_full_name_for_a_top_level_message_is_none() {
+        assert_eq!(owning_message_full_name("Msg"), None);
+    }
+
+    fn assert_compare(expr: &str, field_a: &str, op: CompareOp, rhs: CompareRhs) {
+        assert_eq!(
+            parse_message_constraint(expr).unwrap(),
+            MessageConstraint::Compare { field_a: field_a.to_string(), op, rhs }
+        );
+    }
+
+    #[test]
+    fn parses_field_to_field_comparison() {
+        assert_compare("end_time > start_time", "end_time", CompareOp::Gt, CompareRhs::Field("start_time".to_string()));
+    }
+
+    #[test]
+    fn parses_bare_numeric_literal_as_number() {
+        assert_compare("retries <= 3", "retries", CompareOp::Lte, CompareRhs::Literal(CompareValue::Number(3.0)));
+    }
+
+    #[test]
+    fn parses_quoted_numeral_looking_literal_as_text_not_number() {
+        // A leading zero is only meaningful for a string; parsing it as a number would drop it.
+        assert_compare("zip == \"00501\"", "zip", CompareOp::Eq, CompareRhs::Literal(CompareValue::Text("00501".to_string())));
+    }
+
+    #[test]
+    fn parses_single_quoted_literal_as_text() {
+        assert_compare("status != 'archived'", "status", CompareOp::Ne, CompareRhs::Literal(CompareValue::Text("archived".to_string())));
+    }
+
+    #[test]
+    fn longer_operators_are_tried_before_their_prefixes() {
+        assert_compare("count >= 10", "count", CompareOp::Gte, CompareRhs::Literal(CompareValue::Number(10.0)));
+        assert_compare("count <= 10", "count", CompareOp::Lte, CompareRhs::Literal(CompareValue::Number(10.0)));
+    }
+
+    #[test]
+    fn parses_required_together() {
+        match parse_message_constraint("required_together(client_id, client_secret)").unwrap() {
+            MessageConstraint::RequiredTogether(fields) => assert_eq!(fields, vec!["client_id", "client_secret"]),
+            other => panic!("expected RequiredTogether, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_mutually_exclusive() {
+        match parse_message_constraint("mutually_exclusive(a, b)").unwrap() {
+            MessageConstraint::MutuallyExclusive(fields) => assert_eq!(fields, vec!["a", "b"]),
+            other => panic!("expected MutuallyExclusive, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_unrecognized_expression() {
+        assert!(parse_message_constraint("total ~= 5").is_err());
+    }
+
+    #[test]
+    fn compare_numbers_respects_ordering() {
+        assert!(compare(CompareValue::Number(1.0), CompareOp::Lt, CompareValue::Number(2.0)).is_ok());
+        assert!(compare(CompareValue::Number(2.0), CompareOp::Lt, CompareValue::Number(1.0)).is_err());
+    }
+
+    #[test]
+    fn compare_text_is_lexicographic() {
+        assert!(compare(CompareValue::Text("a".to_string()), CompareOp::Lt, CompareValue::Text("b".to_string())).is_ok());
+        assert!(compare(CompareValue::Text("b".to_string()), CompareOp::Lt, CompareValue::Text("a".to_string())).is_err());
+    }
+
+    #[test]
+    fn compare_bool_only_supports_eq_and_ne() {
+        assert!(compare(CompareValue::Bool(true), CompareOp::Eq, CompareValue::Bool(true)).is_ok());
+        assert!(compare(CompareValue::Bool(true), CompareOp::Ne, CompareValue::Bool(true)).is_err());
+        assert!(compare(CompareValue::Bool(true), CompareOp::Gt, CompareValue::Bool(false)).is_err());
+    }
+
+    #[test]
+    fn compare_rejects_mismatched_types() {
+        assert!(compare(CompareValue::Number(1.0), CompareOp::Eq, CompareValue::Text("1".to_string())).is_err());
+    }
+}